@@ -9,25 +9,33 @@
 /// This file implements Node objects which represent logical nodes in a B-Link Tree. It implements a
 /// thread-safe API for modifying, splitting, and traversing nodes.
 ///----------------------------------------------------------------------------------------------------
-use crate::sync::{BinarySemaphore, LatchType, RwLatch as _, RwSynchronized};
+use crate::sync::{BinarySemaphore, LatchType, RwLatch, RwSynchronized};
 
 /// The internal structure of a node is as follows:
 ///     - `min_ord` contains the minimum order of the node. It is a tree parameter which determines
 ///        the lower bound of the number of keys present in a node.
 ///     - `root` is a boolean value representing whether this node is the tree's root
+///     - `leaf` is a boolean value representing whether this node is a leaf (as opposed to an
+///        internal routing node whose `children` are meaningful)
 ///     - `keys` is a vector of generic keys (must implement PartialOrd + Ord + PartialEq + Eq)
 ///     - `children` is a vector of RwSynchronized Nodes. This is type-aliased to Node<T> below.
 ///        It is a vector of smart pointers, not actual objects
+///     - `high_key` is the exclusive upper bound of the keys this node covers: the separator it was
+///        last split at, which its right sibling holds as its smallest key. Readers compare their
+///        search key against it before descending, and follow `right_link` when the key is greater
+///        than or equal to it, so a node that was split by a concurrent writer, but whose parent has
+///        not yet been updated, is detected and handled.
 ///     - `right_link` is a optional value representing the link of a node to its immediate right
-///        sibling  
+///        sibling
 ///     - `out_link` is an optional value representing the link of a deleted node to a node where
 ///        a thread may resume its search in case it had strayed from the path.
-
 pub struct NodeInner<T> {
     min_ord: usize,
     root: bool,
+    leaf: bool,
     keys: Vec<T>,
     children: Vec<RwSynchronized<NodeInner<T>>>,
+    high_key: Option<T>,
     right_link: Option<RwSynchronized<NodeInner<T>>>,
     out_link: Option<RwSynchronized<NodeInner<T>>>,
 }
@@ -37,14 +45,20 @@ impl<T> NodeInner<T> {
         Self {
             min_ord,
             root: false,
+            leaf: true,
             keys: Vec::new(),
             children: Vec::new(),
+            high_key: None,
             right_link: None,
             out_link: None,
         }
     }
 }
 
+/// `Node<T>` is pinned to the concrete `RwSynchronized` backend rather than generic over `RwLatch<T>`,
+/// since `RwSynchronized` is itself a single backend chosen at compile time by the `spin-lock` feature
+/// (see `sync.rs`). `sync::TicketRwLock` is a fairness-preserving `RwLatch<T>` implementation but is
+/// standalone for that reason - see its doc comment for what backing the tree with it would require.
 pub type Node<T> = RwSynchronized<NodeInner<T>>;
 
 /// Methods for generic BTreeNodes
@@ -53,15 +67,33 @@ pub trait BTreeNode<T> {
     fn move_right(&self, key: &T, latch_type: LatchType) -> Node<T>;
     fn has_key(&self, key: &T) -> bool;
     fn is_root(&self) -> bool;
+    fn set_root(&self, root: bool);
+    fn is_leaf(&self) -> bool;
+    fn set_leaf(&self, leaf: bool);
     fn set_keys(&self, keys: Vec<T>);
     fn set_children(&self, children: Vec<Node<T>>);
+    fn high_key(&self) -> Option<&T>;
+    fn set_high_key(&self, key: Option<T>);
+    fn right_link(&self) -> Option<Node<T>>;
+    fn set_right_link(&self, link: Option<Node<T>>);
+    fn out_link(&self) -> Option<Node<T>>;
+    fn set_out_link(&self, link: Option<Node<T>>);
+    fn child_index(&self, key: &T) -> usize;
+    fn child_at(&self, index: usize) -> Node<T>;
+    fn child_slot(&self, child: &Node<T>) -> Option<usize>;
+    fn key_count(&self) -> usize;
+    fn keys_snapshot(&self) -> Vec<T>;
+    fn insert_key(&self, key: T);
+    fn insert_separator(&self, separator: T, right: Node<T>);
+    fn remove_key(&self, key: &T) -> bool;
+    fn remove_child(&self, index: usize);
+    fn split(&self) -> (T, Node<T>);
     fn would_overflow(&self) -> bool;
-    fn would_underflow(&self) -> bool;
 }
 
 impl<T> BTreeNode<T> for Node<T>
 where
-    T: Ord + PartialOrd,
+    T: Ord + PartialOrd + Clone,
 {
     fn create(min_ord: usize) -> Node<T> {
         RwSynchronized::init(NodeInner::new(min_ord))
@@ -70,7 +102,7 @@ where
     /// Check whether the given key is in the node. Must have a latch or RAII guard on the node for safety.
     fn has_key(&self, key: &T) -> bool {
         let inner = unsafe { &(*self.data_ptr()) };
-        inner.keys.binary_search(&key).is_err()
+        inner.keys.binary_search(key).is_ok()
     }
 
     /// Check whether a node is the root. Must have a latch or RAII guard on the node for safety
@@ -79,10 +111,61 @@ where
         inner.root
     }
 
-    /// Move right until we are at the node at which they key would exist if it exists
-    fn move_right(&self, key: &T, latch_type: LatchType) -> Node<T> {
+    /// Mark or unmark a node as the tree's root. Must hold an exclusive latch on the node.
+    fn set_root(&self, root: bool) {
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        inner.root = root;
+    }
+
+    /// Check whether a node is a leaf. Must have a latch or RAII guard on the node for safety
+    fn is_leaf(&self) -> bool {
         let inner = unsafe { &(*self.data_ptr()) };
-        Node::create(0)
+        inner.leaf
+    }
+
+    /// Mark or unmark a node as a leaf. Must hold an exclusive latch on the node.
+    fn set_leaf(&self, leaf: bool) {
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        inner.leaf = leaf;
+    }
+
+    /// Move right until we reach the node whose high key is greater than `key` (or that has no high
+    /// key), taking
+    /// `latch_type` on every node hopped to and releasing it on every node left behind (including the
+    /// node the method is called on). The caller must already hold `latch_type` on `self`; the node
+    /// returned is held under `latch_type` by the caller.
+    fn move_right(&self, key: &T, latch_type: LatchType) -> Node<T> {
+        let mut current = self.clone();
+        loop {
+            let exceeds_high_key = {
+                let inner = unsafe { &(*current.data_ptr()) };
+                match &inner.high_key {
+                    Some(high_key) => key >= high_key,
+                    None => false,
+                }
+            };
+            if !exceeds_high_key {
+                return current;
+            }
+            let right = {
+                let inner = unsafe { &(*current.data_ptr()) };
+                inner
+                    .right_link
+                    .clone()
+                    .expect("a node with a high key must have a right link")
+            };
+            match latch_type {
+                LatchType::Shared => right.acquire_shared(),
+                LatchType::Upgradable => right.acquire_upgradable(),
+                LatchType::Excl => right.acquire_excl(),
+            }
+            match latch_type {
+                LatchType::Shared => current.release_shared(),
+                LatchType::Upgradable => current.release_upgradable(),
+                LatchType::Excl => current.release_excl(),
+            }
+            current = right;
+        }
     }
 
     /// Set the children of a node to a vector of Node<T>
@@ -97,35 +180,225 @@ where
         inner.keys = keys;
     }
 
-    /// Return true if the node is in danger of overflowing
-    fn would_overflow(&self) -> bool {
+    /// Read the node's high key. Must have a latch or RAII guard on the node for safety.
+    fn high_key(&self) -> Option<&T> {
+        let inner = unsafe { &(*self.data_ptr()) };
+        inner.high_key.as_ref()
+    }
+
+    /// Set the node's high key. Must hold an exclusive latch on the node.
+    fn set_high_key(&self, key: Option<T>) {
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        inner.high_key = key;
+    }
+
+    /// Clone the link to the node's right sibling, if any.
+    fn right_link(&self) -> Option<Node<T>> {
+        let inner = unsafe { &(*self.data_ptr()) };
+        inner.right_link.clone()
+    }
+
+    /// Set the node's right link. Must hold an exclusive latch on the node.
+    fn set_right_link(&self, link: Option<Node<T>>) {
         let inner = unsafe { &mut (*self.data_ptr()) };
-        inner.keys.len() == inner.min_ord
+        inner.right_link = link;
     }
 
-    /// Return true if the node is in danger of underflowing
-    fn would_underflow(&self) -> bool {
+    /// Clone the node's out-link, if any.
+    fn out_link(&self) -> Option<Node<T>> {
+        let inner = unsafe { &(*self.data_ptr()) };
+        inner.out_link.clone()
+    }
+
+    /// Set the node's out-link. Must hold an exclusive latch on the node.
+    fn set_out_link(&self, link: Option<Node<T>>) {
         let inner = unsafe { &mut (*self.data_ptr()) };
-        inner.keys.len() == 2 * inner.min_ord
+        inner.out_link = link;
+    }
+
+    /// Return the number of keys currently stored in the node.
+    fn key_count(&self) -> usize {
+        let inner = unsafe { &(*self.data_ptr()) };
+        inner.keys.len()
+    }
+
+    /// Clone out the node's keys. Must have a latch or RAII guard on the node for safety.
+    fn keys_snapshot(&self) -> Vec<T> {
+        let inner = unsafe { &(*self.data_ptr()) };
+        inner.keys.clone()
+    }
+
+    /// For an internal node, return the index of the child that would hold `key`. This is the index
+    /// of the first key strictly greater than `key`.
+    fn child_index(&self, key: &T) -> usize {
+        let inner = unsafe { &(*self.data_ptr()) };
+        match inner.keys.binary_search(key) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+
+    /// Clone the child link at `index`. Must have a latch or RAII guard on the node for safety.
+    fn child_at(&self, index: usize) -> Node<T> {
+        let inner = unsafe { &(*self.data_ptr()) };
+        inner.children[index].clone()
+    }
+
+    /// Find the index of `child` among this node's children by pointer identity. Must have a latch
+    /// or RAII guard on the node for safety.
+    fn child_slot(&self, child: &Node<T>) -> Option<usize> {
+        let inner = unsafe { &(*self.data_ptr()) };
+        inner
+            .children
+            .iter()
+            .position(|c| RwLatch::ptr_eq(c, child))
+    }
+
+    /// Insert `key` into a leaf's key vector, keeping it sorted. Must hold an exclusive latch on the
+    /// node.
+    fn insert_key(&self, key: T) {
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        match inner.keys.binary_search(&key) {
+            Ok(index) => inner.keys[index] = key,
+            Err(index) => inner.keys.insert(index, key),
+        }
+    }
+
+    /// Insert a separator key produced by a child split, along with the link to the new right child.
+    /// Must hold an exclusive latch on the node.
+    fn insert_separator(&self, separator: T, right: Node<T>) {
+        let index = self.child_index(&separator);
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        inner.keys.insert(index, separator);
+        inner.children.insert(index + 1, right);
+    }
+
+    /// Remove `key` from a leaf's key vector, returning whether it was present. Must hold an
+    /// exclusive latch on the node.
+    fn remove_key(&self, key: &T) -> bool {
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        match inner.keys.binary_search(key) {
+            Ok(index) => {
+                inner.keys.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Remove the child at `index` along with the key that routes to it (the key to its left, unless
+    /// it is the leftmost child). Must hold an exclusive latch on the node.
+    fn remove_child(&self, index: usize) {
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        inner.children.remove(index);
+        inner.keys.remove(if index == 0 { 0 } else { index - 1 });
+    }
+
+    /// Split an overflowing node in half, moving the upper half of its keys (and children, if any)
+    /// into a freshly allocated right sibling. The new sibling's `right_link` takes over the old
+    /// node's `right_link`, the old node's `right_link` is repointed at the new sibling, and both
+    /// nodes' high keys are updated. Returns the separator key to be propagated up into the parent,
+    /// along with the new sibling itself. For a leaf, the separator is copied up (it also remains the
+    /// smallest key held by the new right sibling, per B-link/B+-tree convention); for an internal
+    /// node the separator is the middle key and is removed from both sides, since an internal node
+    /// with `n` keys must keep `n + 1` children. Must hold an exclusive latch on the node; the caller
+    /// retains that latch on return.
+    fn split(&self) -> (T, Node<T>) {
+        let new_right = Node::create(0);
+        let separator = {
+            let inner = unsafe { &mut (*self.data_ptr()) };
+            let mid = inner.keys.len() / 2;
+            let (separator, upper_keys, upper_children) = if inner.leaf {
+                let upper_keys = inner.keys.split_off(mid);
+                let separator = upper_keys[0].clone();
+                (separator, upper_keys, Vec::new())
+            } else {
+                let upper_keys = inner.keys.split_off(mid + 1);
+                let separator = inner
+                    .keys
+                    .pop()
+                    .expect("internal node must have a midpoint key to split on");
+                let upper_children = inner.children.split_off(mid + 1);
+                (separator, upper_keys, upper_children)
+            };
+
+            let right_inner = unsafe { &mut (*new_right.data_ptr()) };
+            right_inner.min_ord = inner.min_ord;
+            right_inner.leaf = inner.leaf;
+            right_inner.keys = upper_keys;
+            right_inner.children = upper_children;
+            right_inner.high_key = inner.high_key.clone();
+            right_inner.right_link = inner.right_link.take();
+
+            inner.high_key = Some(separator.clone());
+            inner.right_link = Some(new_right.clone());
+            separator
+        };
+        (separator, new_right)
+    }
+
+    /// Return true if the node holds more than the maximum number of keys and must be split. Merging
+    /// on underflow is out of scope for this tree: `delete` only unlinks leaves that become fully
+    /// empty, so there is no underflow check and no merge path.
+    fn would_overflow(&self) -> bool {
+        let inner = unsafe { &mut (*self.data_ptr()) };
+        inner.keys.len() > 2 * inner.min_ord
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{BTreeNode, Node};
+    use crate::sync::{LatchType, RwLatch as _};
 
     #[test]
     fn test_create() {
         // Testing creation
         let node: Node<usize> = Node::create(2);
         let inner = unsafe { &mut (*node.data_ptr()) };
-        assert!(inner.root == false);
+        assert!(!inner.root);
+        assert!(inner.leaf);
         assert!(inner.right_link.is_none());
         assert!(inner.out_link.is_none());
-        assert!(inner.children.len() == 0);
-        assert!(inner.keys.len() == 0);
+        assert!(inner.children.is_empty());
+        assert!(inner.keys.is_empty());
         assert!(inner.min_ord == 2);
 
         // Testing setters
+        node.set_keys(vec![1, 2, 3]);
+        assert!(node.has_key(&2));
+        assert!(!node.has_key(&4));
+    }
+
+    #[test]
+    fn test_split_leaf() {
+        let node: Node<usize> = Node::create(2);
+        node.acquire_excl();
+        node.set_keys(vec![1, 2, 3, 4]);
+        let (separator, right) = node.split();
+        assert_eq!(separator, 3);
+        right.acquire_shared();
+        assert!(right.has_key(&3));
+        assert!(right.has_key(&4));
+        assert!(!node.has_key(&3));
+        assert!(node.has_key(&1));
+        assert_eq!(node.high_key(), Some(&3));
+        right.release_shared();
+        node.release_excl();
+    }
+
+    #[test]
+    fn test_move_right_hops_past_split_sibling() {
+        let left: Node<usize> = Node::create(2);
+        let right: Node<usize> = Node::create(2);
+        right.set_keys(vec![3, 4]);
+        left.set_keys(vec![1, 2]);
+        left.set_high_key(Some(2));
+        left.set_right_link(Some(right.clone()));
+
+        left.acquire_excl();
+        let found = left.move_right(&4, LatchType::Excl);
+        assert!(found.has_key(&4));
+        found.release_excl();
     }
 }
@@ -0,0 +1,119 @@
+#![allow(unused)]
+
+///----------------------------------------------------------------------------------------------------
+/// The author disclaims copyright to this source code. In place of a legal notice, here is a blessing:
+///     May you do good and not evil.
+///     May you find forgiveness for yourself and forgive others.
+///     May you share freely, never taking more than you give.
+///----------------------------------------------------------------------------------------------------
+/// This file implements `ShardedTree<T>`, a facade over several independent `Tree<T>` instances keyed
+/// by hash, borrowing the same technique as `sync::Sharded`: rather than every insert/search
+/// contending on one tree's root latch, a key is hashed to one of N trees up front, and callers only
+/// ever contend with others whose keys land on the same tree. Each underlying `Tree` is already
+/// internally concurrent (readers and writers latch-crab past each other via the B-link protocol), so
+/// sharding on top of it is purely about spreading *root*-latch contention across N roots instead of
+/// giving every caller the same one - the shards don't need an extra lock of their own the way
+/// `sync::Sharded<T>`'s plain `T` payloads do.
+///----------------------------------------------------------------------------------------------------
+use crate::tree::{BTree as _, Tree};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash};
+
+pub struct ShardedTree<T> {
+    shards: Vec<Tree<T>>,
+}
+
+impl<T> ShardedTree<T>
+where
+    T: Ord + PartialOrd + Clone + Hash,
+{
+    /// Build a sharded tree with one shard per available CPU (falling back to a single shard if the
+    /// platform can't report a count), each a `Tree::new(min_ord)`.
+    pub fn new(min_ord: usize) -> Self {
+        let shard_count = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        Self::with_shard_count(shard_count, min_ord)
+    }
+
+    /// Build a sharded tree with an explicit shard count (rounded up to 1).
+    pub fn with_shard_count(shard_count: usize, min_ord: usize) -> Self {
+        let shard_count = shard_count.max(1);
+        let shards = (0..shard_count).map(|_| Tree::new(min_ord)).collect();
+        Self { shards }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_for(&self, key: &T) -> &Tree<T> {
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(key);
+        &self.shards[(hash as usize) % self.shards.len()]
+    }
+
+    pub fn search(&self, key: &T) -> bool {
+        self.shard_for(key).search(key)
+    }
+
+    pub fn insert(&self, key: T) {
+        self.shard_for(&key).insert(key)
+    }
+
+    pub fn delete(&self, key: &T) -> bool {
+        self.shard_for(key).delete(key)
+    }
+
+    /// Collect every key across all shards, locking one shard's leaf chain at a time rather than the
+    /// whole structure at once.
+    pub fn keys(&self) -> Vec<T> {
+        let mut collected = Vec::new();
+        for shard in &self.shards {
+            collected.extend(shard.keys());
+        }
+        collected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShardedTree;
+    use rayon::ThreadPoolBuilder;
+
+    #[test]
+    fn test_single_threaded_insert_search_delete() {
+        let tree: ShardedTree<usize> = ShardedTree::with_shard_count(4, 2);
+        for key in 0..100usize {
+            tree.insert(key);
+        }
+        for key in 0..100usize {
+            assert!(tree.search(&key));
+        }
+        assert!(!tree.search(&1000));
+        assert!(tree.delete(&50));
+        assert!(!tree.search(&50));
+
+        let mut keys = tree.keys();
+        keys.sort_unstable();
+        assert_eq!(keys.len(), 99);
+    }
+
+    #[test]
+    fn test_concurrent_insert_across_shards() {
+        let tree: ShardedTree<usize> = ShardedTree::with_shard_count(8, 4);
+        let pool = ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        pool.scope(|scope| {
+            for worker in 0..8usize {
+                let tree = &tree;
+                scope.spawn(move |_| {
+                    for i in 0..50usize {
+                        tree.insert(worker * 50 + i);
+                    }
+                });
+            }
+        });
+        for key in 0..400usize {
+            assert!(tree.search(&key));
+        }
+    }
+}
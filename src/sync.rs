@@ -7,23 +7,55 @@
 /// This file implements synchronization primitives and methods for synchronization primitives used
 /// throughout the implementation of the tree. Specifically, this file contains a correct
 /// implementation of a binary semaphore as well as latch/unlatch methods for synchronized objects
-/// (both protected by mutexes and protected by rwlocks). The mutexes are `parking_lot::Mutex` and
-/// the rwlocks are `parking_lot::RwLock` (not std::sync::Mutex/std::sync::RwLock).
+/// (both protected by mutexes and protected by rwlocks).
+///
+/// `Synchronized<T>`/`RwSynchronized<T>` are backed by one of two interchangeable backends, chosen at
+/// compile time by the `spin-lock` Cargo feature:
+///     - by default, `parking_lot::Mutex`/`parking_lot::RwLock` (see `sync::parking`), which parks
+///       blocked threads with the OS scheduler;
+///     - with `spin-lock` enabled, a busy-waiting backend built on `AtomicUsize` (see
+///       `sync::spin`) that has no OS dependency and is usable in `no_std` + `alloc` contexts such
+///       as embedded firmware.
+/// Both backends implement the same `Latch<T>`/`RwLatch<T>` surface, so code written against those
+/// traits (e.g. the B-link tree's latch-crabbing) is portable across either choice.
+///
+/// `ticket` additionally offers `TicketRwLock<T>`, a fairness-preserving `RwLatch<T>` implementation
+/// for callers who need bounded wait times under read-heavy contention rather than the raw throughput
+/// `parking_lot::RwLock` optimizes for; see that module for details.
+///
+/// `asynchronous` offers `AsyncMutex<T>`/`AsyncRwLatch<T>`/`AsyncBinarySemaphore`, which park the
+/// calling *task* (via `std::task::Waker`) instead of blocking the calling *thread*, for use from
+/// within an async runtime such as Tokio; see that module for details.
+///
+/// `sharded` offers `Sharded<T>`, a fixed-size array of independently-latched shards that spreads
+/// contention across several `RwSynchronized<T>`s instead of funneling every caller through one; see
+/// that module for details.
 ///----------------------------------------------------------------------------------------------------
-use parking_lot::lock_api::{RawMutex as _, RawRwLock as _, RawRwLockUpgrade as _};
-use parking_lot::{Condvar, Mutex, RwLock};
+use parking_lot::{Condvar, Mutex};
 use std::sync::Arc;
 
+mod asynchronous;
+#[cfg(not(feature = "spin-lock"))]
+mod parking;
+mod sharded;
+#[cfg(feature = "spin-lock")]
+mod spin;
+mod ticket;
+
+pub use asynchronous::{AsyncBinarySemaphore, AsyncMutex, AsyncRwLatch};
+#[cfg(not(feature = "spin-lock"))]
+pub use parking::{RwSynchronized, Synchronized};
+pub use sharded::Sharded;
+#[cfg(feature = "spin-lock")]
+pub use spin::{RelaxStrategy, RwSynchronized, SpinRelax, Synchronized};
+#[cfg(all(feature = "spin-lock", feature = "std"))]
+pub use spin::YieldRelax;
+pub use ticket::TicketRwLock;
+
 /// BinarySemaphore: Semaphore with two states. Useful for setup tasks or making the main thread wait. Prefer using condvars if you're
 /// trying to synchronize threads though.
 pub type BinarySemaphore = Arc<(Mutex<bool>, Condvar)>;
 
-/// Protect anything with a Mutex. Can pass between threads (implements the clone trait)
-pub type Synchronized<T> = Arc<Mutex<T>>;
-
-/// Protect anything with a RwLock. Can pass between threads (implements the clone trait).
-pub type RwSynchronized<T> = Arc<RwLock<T>>;
-
 /// Use this to specify the latch type
 #[allow(unused)]
 #[derive(PartialEq, Eq)]
@@ -57,6 +89,10 @@ pub trait RwLatch<T> {
     fn release_upgradable(&self);
     fn release_excl(&self);
     fn upgrade_shared(&self);
+    /// Compare two handles for identity (do they refer to the same underlying lock?), regardless of
+    /// which backend is selected. Used to find a specific node among a parent's children by pointer
+    /// rather than by key.
+    fn ptr_eq(a: &Self, b: &Self) -> bool;
 }
 
 /// Implement most of the POSIX Semaphore API (init/post/wait) but not value
@@ -82,87 +118,11 @@ impl BinarySemaphoreMethods for BinarySemaphore {
     }
 }
 
-/// The methods here are for latching Synchronized<T> objects *unsafely*. Don't use this unless you have to (prefer RAII guards)
-/// Examples of when you need to use these methods:
-/// - If you need to place a lock on an object in one function and unlock it in another function (i.e. when you can't do everything you)
-///   want in one scope.
-impl<T> Latch<T> for Synchronized<T> {
-    fn init(item: T) -> Self {
-        Arc::new(Mutex::new(item))
-    }
-    fn latch(&self) {
-        unsafe {
-            self.raw().lock();
-        }
-    }
-    fn unlatch(&self) {
-        unsafe {
-            self.raw().unlock();
-        }
-    }
-}
-
-/// The methods here are for latching RwSynchronized<T> objects *unsafely*. Don't use this unless you have to (prefer RAII guards)
-/// Examples of when you need to use these methods:
-/// - If you need to place a lock on an object in one function and unlock it in another function (i.e. when you can't do everything you)
-///   want in one scope.
-impl<T> RwLatch<T> for RwSynchronized<T> {
-    fn init(item: T) -> Self {
-        Arc::new(RwLock::new(item))
-    }
-
-    /// Acquire a shared lock. Must not hold a lock in the current context.
-    fn acquire_shared(&self) {
-        unsafe {
-            self.raw().lock_shared();
-        }
-    }
-
-    /// Acquire an upgradable lock. Must not hold a lock in the current context.
-    fn acquire_upgradable(&self) {
-        unsafe {
-            self.raw().lock_upgradable();
-        }
-    }
-
-    /// Acquire an exclusive lock. Must not hold a lock in the current context.
-    fn acquire_excl(&self) {
-        unsafe {
-            self.raw().lock_exclusive();
-        }
-    }
-
-    /// Release a shared lock. Must hold a shared lock in the current context.
-    fn release_shared(&self) {
-        unsafe {
-            self.raw().unlock_shared();
-        }
-    }
-
-    /// Release an upgradable lock. Must hold an upgradable lock in the current context.
-    fn release_upgradable(&self) {
-        unsafe {
-            self.raw().unlock_upgradable();
-        }
-    }
-
-    /// Release an exclusive lock. Must hold an exclusive lock in the current context (upgradable locks upgraded to exclusive qualify).
-    fn release_excl(&self) {
-        unsafe {
-            self.raw().unlock_exclusive();
-        }
-    }
-
-    /// Upgrade an upgradable lock to an exclusive one. Must hold an upgradable lock in the current context that has not yet been
-    /// upgraded
-    fn upgrade_shared(&self) {
-        unsafe {
-            self.raw().upgrade();
-        }
-    }
-}
-
-#[cfg(test)]
+// These tests exercise `RwSynchronized`/`Synchronized` through the raw `parking_lot` guard API
+// (`.read()`/`.write()`/`.data_ptr()`) in addition to the `Latch`/`RwLatch` traits, so they only apply
+// to the default backend. The spin backend has its own tests in `sync::spin`, scoped to the trait
+// surface that both backends share.
+#[cfg(all(test, not(feature = "spin-lock")))]
 mod tests {
     use rayon::ThreadPoolBuilder;
 
@@ -0,0 +1,557 @@
+///----------------------------------------------------------------------------------------------------
+/// The author disclaims copyright to this source code. In place of a legal notice, here is a blessing:
+///     May you do good and not evil.
+///     May you find forgiveness for yourself and forgive others.
+///     May you share freely, never taking more than you give.
+///----------------------------------------------------------------------------------------------------
+/// This file implements async-aware counterparts to the blocking primitives elsewhere in `sync`:
+/// `AsyncMutex<T>`, `AsyncRwLatch<T>`, and `AsyncBinarySemaphore`. Every other latch type in this
+/// crate blocks the calling OS thread while it waits, which is exactly wrong inside an async runtime,
+/// since a blocked worker thread can't run any other task, including the one that would eventually
+/// release the lock. These types instead model their state as an atomic word plus a queue of parked
+/// `Waker`s: a pending acquirer stores its task's `Waker` and returns `Poll::Pending`, and a releaser
+/// drains the queue and wakes everyone who might now be able to proceed (they re-check the state
+/// themselves on the next poll, so a spurious wake just costs a retry, never correctness). None of
+/// this depends on any particular executor; it works under Tokio, async-std, or a hand-rolled one.
+///----------------------------------------------------------------------------------------------------
+use parking_lot::Mutex as WakerQueueMutex;
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+const WRITER: usize = 1 << (usize::BITS - 1);
+const UPGRADABLE: usize = 1 << (usize::BITS - 2);
+const READER: usize = 1;
+
+/// A FIFO-ish parking list of task wakers. Acquisitions register here when they can't proceed and are
+/// woken (to retry, not to assume success) whenever a release might have changed that.
+struct WaiterQueue {
+    wakers: WakerQueueMutex<VecDeque<Waker>>,
+}
+
+impl WaiterQueue {
+    fn new() -> Self {
+        Self {
+            wakers: WakerQueueMutex::new(VecDeque::new()),
+        }
+    }
+
+    fn park(&self, waker: &Waker) {
+        self.wakers.lock().push_back(waker.clone());
+    }
+
+    fn wake_all(&self) {
+        let mut queue = self.wakers.lock();
+        while let Some(waker) = queue.pop_front() {
+            waker.wake();
+        }
+    }
+}
+
+struct AsyncMutexInner<T> {
+    locked: AtomicUsize,
+    waiters: WaiterQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for AsyncMutexInner<T> {}
+unsafe impl<T: Send> Sync for AsyncMutexInner<T> {}
+
+/// The async-aware analogue of `Synchronized<T>`/`Latch<T>`. `lock().await` yields an RAII guard that
+/// releases on drop, same as the blocking version, but never occupies an executor thread while waiting.
+pub struct AsyncMutex<T> {
+    inner: Arc<AsyncMutexInner<T>>,
+}
+
+impl<T> Clone for AsyncMutex<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> AsyncMutex<T> {
+    pub fn init(item: T) -> Self {
+        Self {
+            inner: Arc::new(AsyncMutexInner {
+                locked: AtomicUsize::new(0),
+                waiters: WaiterQueue::new(),
+                data: UnsafeCell::new(item),
+            }),
+        }
+    }
+
+    /// Acquire the lock, parking the calling task rather than the calling thread while it waits.
+    pub fn lock(&self) -> AsyncMutexLockFuture<'_, T> {
+        AsyncMutexLockFuture { mutex: self }
+    }
+}
+
+pub struct AsyncMutexLockFuture<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+impl<'a, T> Future for AsyncMutexLockFuture<'a, T> {
+    type Output = AsyncMutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self
+            .mutex
+            .inner
+            .locked
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return Poll::Ready(AsyncMutexGuard { mutex: self.mutex });
+        }
+        self.mutex.inner.waiters.park(cx.waker());
+        // Re-check after registering: the lock may have been released between the failed attempt
+        // above and the park() call, in which case we'd otherwise wait for a wakeup that already
+        // happened.
+        if self
+            .mutex
+            .inner
+            .locked
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return Poll::Ready(AsyncMutexGuard { mutex: self.mutex });
+        }
+        Poll::Pending
+    }
+}
+
+/// RAII guard returned by `AsyncMutex::lock`. Releases the lock and wakes every parked waiter on drop.
+pub struct AsyncMutexGuard<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+impl<T> Deref for AsyncMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.inner.data.get() }
+    }
+}
+
+impl<T> DerefMut for AsyncMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.inner.data.get() }
+    }
+}
+
+impl<T> Drop for AsyncMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.inner.locked.store(0, Ordering::Release);
+        self.mutex.inner.waiters.wake_all();
+    }
+}
+
+struct AsyncRwLatchInner<T> {
+    state: AtomicUsize,
+    waiters: WaiterQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for AsyncRwLatchInner<T> {}
+unsafe impl<T: Send> Sync for AsyncRwLatchInner<T> {}
+
+/// The async-aware analogue of `RwSynchronized<T>`/`RwLatch<T>`, using the same state-word layout as
+/// `sync::spin`'s `RwSpinLock` (top bit exclusive, next bit the single-upgrader reservation, remaining
+/// bits a reader count) but parking the task instead of spinning when it can't immediately proceed.
+pub struct AsyncRwLatch<T> {
+    inner: Arc<AsyncRwLatchInner<T>>,
+}
+
+impl<T> Clone for AsyncRwLatch<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> AsyncRwLatch<T> {
+    pub fn init(item: T) -> Self {
+        Self {
+            inner: Arc::new(AsyncRwLatchInner {
+                state: AtomicUsize::new(0),
+                waiters: WaiterQueue::new(),
+                data: UnsafeCell::new(item),
+            }),
+        }
+    }
+
+    /// Acquire a shared guard, parking the task rather than the thread while a writer holds the lock.
+    pub fn acquire_shared(&self) -> AcquireSharedFuture<'_, T> {
+        AcquireSharedFuture { lock: self }
+    }
+
+    /// Acquire an upgradable guard. At most one may be outstanding at a time; a second upgrader parks
+    /// until the first releases its upgradable guard (or, if it upgraded, its exclusive guard). It
+    /// also parks while any writer holds the lock.
+    pub fn acquire_upgradable(&self) -> AcquireUpgradableFuture<'_, T> {
+        AcquireUpgradableFuture { lock: self }
+    }
+
+    /// Acquire an exclusive guard, parking the task until the writer bit, the upgradable bit and the
+    /// reader count are all clear.
+    pub fn acquire_excl(&self) -> AcquireExclFuture<'_, T> {
+        AcquireExclFuture { lock: self }
+    }
+}
+
+pub struct AcquireSharedFuture<'a, T> {
+    lock: &'a AsyncRwLatch<T>,
+}
+
+impl<'a, T> Future for AcquireSharedFuture<'a, T> {
+    type Output = AsyncRwLatchReadGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let state = self.lock.inner.state.load(Ordering::Relaxed);
+            if state & WRITER == 0 {
+                if self
+                    .lock
+                    .inner
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state + READER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return Poll::Ready(AsyncRwLatchReadGuard { lock: self.lock });
+                }
+                continue;
+            }
+            self.lock.inner.waiters.park(cx.waker());
+            if self.lock.inner.state.load(Ordering::Relaxed) & WRITER == 0 {
+                continue;
+            }
+            return Poll::Pending;
+        }
+    }
+}
+
+pub struct AcquireUpgradableFuture<'a, T> {
+    lock: &'a AsyncRwLatch<T>,
+}
+
+impl<'a, T> Future for AcquireUpgradableFuture<'a, T> {
+    type Output = AsyncRwLatchUpgradableGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let state = self.lock.inner.state.load(Ordering::Relaxed);
+            if state & (WRITER | UPGRADABLE) == 0 {
+                if self
+                    .lock
+                    .inner
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state | UPGRADABLE,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return Poll::Ready(AsyncRwLatchUpgradableGuard { lock: self.lock });
+                }
+                continue;
+            }
+            self.lock.inner.waiters.park(cx.waker());
+            if self.lock.inner.state.load(Ordering::Relaxed) & (WRITER | UPGRADABLE) == 0 {
+                continue;
+            }
+            return Poll::Pending;
+        }
+    }
+}
+
+pub struct AcquireExclFuture<'a, T> {
+    lock: &'a AsyncRwLatch<T>,
+}
+
+impl<'a, T> Future for AcquireExclFuture<'a, T> {
+    type Output = AsyncRwLatchWriteGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self
+            .lock
+            .inner
+            .state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return Poll::Ready(AsyncRwLatchWriteGuard { lock: self.lock });
+        }
+        self.lock.inner.waiters.park(cx.waker());
+        if self
+            .lock
+            .inner
+            .state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return Poll::Ready(AsyncRwLatchWriteGuard { lock: self.lock });
+        }
+        Poll::Pending
+    }
+}
+
+/// RAII guard returned by `AsyncRwLatch::acquire_shared`. Releases on drop.
+pub struct AsyncRwLatchReadGuard<'a, T> {
+    lock: &'a AsyncRwLatch<T>,
+}
+
+impl<T> Deref for AsyncRwLatchReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.inner.data.get() }
+    }
+}
+
+impl<T> Drop for AsyncRwLatchReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.inner.state.fetch_sub(READER, Ordering::Release);
+        self.lock.inner.waiters.wake_all();
+    }
+}
+
+/// RAII guard returned by `AsyncRwLatch::acquire_upgradable`. Releases on drop unless consumed by
+/// `upgrade`.
+pub struct AsyncRwLatchUpgradableGuard<'a, T> {
+    lock: &'a AsyncRwLatch<T>,
+}
+
+impl<T> Deref for AsyncRwLatchUpgradableGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.inner.data.get() }
+    }
+}
+
+impl<'a, T> AsyncRwLatchUpgradableGuard<'a, T> {
+    /// Upgrade to an exclusive guard, parking the task until every other reader has drained.
+    pub fn upgrade(self) -> UpgradeFuture<'a, T> {
+        let lock = self.lock;
+        std::mem::forget(self); // ownership of the UPGRADABLE bit passes into UpgradeFuture
+        UpgradeFuture { lock }
+    }
+}
+
+impl<T> Drop for AsyncRwLatchUpgradableGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock
+            .inner
+            .state
+            .fetch_and(!UPGRADABLE, Ordering::Release);
+        self.lock.inner.waiters.wake_all();
+    }
+}
+
+pub struct UpgradeFuture<'a, T> {
+    lock: &'a AsyncRwLatch<T>,
+}
+
+impl<'a, T> Future for UpgradeFuture<'a, T> {
+    type Output = AsyncRwLatchWriteGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Unlike an upgradable acquisition, which never blocks concurrent readers, the upgradable
+        // guard itself does not count as a reader - so this waits for the reader count to drain to
+        // zero, then swaps the reservation bit for the writer bit.
+        if self
+            .lock
+            .inner
+            .state
+            .compare_exchange(UPGRADABLE, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return Poll::Ready(AsyncRwLatchWriteGuard { lock: self.lock });
+        }
+        self.lock.inner.waiters.park(cx.waker());
+        if self
+            .lock
+            .inner
+            .state
+            .compare_exchange(UPGRADABLE, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return Poll::Ready(AsyncRwLatchWriteGuard { lock: self.lock });
+        }
+        Poll::Pending
+    }
+}
+
+/// RAII guard returned by `AsyncRwLatch::acquire_excl` or `AsyncRwLatchUpgradableGuard::upgrade`.
+/// Releases on drop.
+pub struct AsyncRwLatchWriteGuard<'a, T> {
+    lock: &'a AsyncRwLatch<T>,
+}
+
+impl<T> Deref for AsyncRwLatchWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.inner.data.get() }
+    }
+}
+
+impl<T> DerefMut for AsyncRwLatchWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.inner.data.get() }
+    }
+}
+
+impl<T> Drop for AsyncRwLatchWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.inner.state.store(0, Ordering::Release);
+        self.lock.inner.waiters.wake_all();
+    }
+}
+
+struct AsyncBinarySemaphoreInner {
+    state: AtomicUsize,
+    waiters: WaiterQueue,
+}
+
+/// The async-aware analogue of `BinarySemaphore`/`BinarySemaphoreMethods`. `wait().await` parks the
+/// task rather than blocking a condvar until `post` flips the state to `true`.
+#[derive(Clone)]
+pub struct AsyncBinarySemaphore {
+    inner: Arc<AsyncBinarySemaphoreInner>,
+}
+
+impl AsyncBinarySemaphore {
+    pub fn init(state: bool) -> Self {
+        Self {
+            inner: Arc::new(AsyncBinarySemaphoreInner {
+                state: AtomicUsize::new(state as usize),
+                waiters: WaiterQueue::new(),
+            }),
+        }
+    }
+
+    /// Flip the semaphore's state and wake every task parked in `wait`.
+    pub fn post(&self) {
+        self.inner.state.fetch_xor(1, Ordering::Release);
+        self.inner.waiters.wake_all();
+    }
+
+    /// Park the calling task until the semaphore's state is `true`, returning that state (always
+    /// `true`), mirroring `BinarySemaphoreMethods::wait`'s signature.
+    pub fn wait(&self) -> WaitFuture<'_> {
+        WaitFuture { semaphore: self }
+    }
+}
+
+pub struct WaitFuture<'a> {
+    semaphore: &'a AsyncBinarySemaphore,
+}
+
+impl<'a> Future for WaitFuture<'a> {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.semaphore.inner.state.load(Ordering::Acquire) != 0 {
+            return Poll::Ready(true);
+        }
+        self.semaphore.inner.waiters.park(cx.waker());
+        if self.semaphore.inner.state.load(Ordering::Acquire) != 0 {
+            return Poll::Ready(true);
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::{RawWaker, RawWakerVTable};
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw()
+        }
+        fn wake(_: *const ()) {}
+        fn wake_by_ref(_: *const ()) {}
+        fn drop(_: *const ()) {}
+        fn raw() -> RawWaker {
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+        unsafe { Waker::from_raw(raw()) }
+    }
+
+    /// Busy-poll a future to completion on the calling thread with a no-op waker, standing in for a
+    /// real executor. None of these futures are expected to stay `Pending` forever - that is exactly
+    /// the bug this module's futures have shipped with before - so a tight re-poll loop is enough to
+    /// exercise them without pulling in an external executor dependency.
+    fn block_on<F: Future>(mut future: F) -> F::Output {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = unsafe { Pin::new_unchecked(&mut future) };
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => std::hint::spin_loop(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_async_mutex_lock_unlock() {
+        let mutex = AsyncMutex::init(0usize);
+        {
+            let mut guard = block_on(mutex.lock());
+            *guard += 1;
+        }
+        let guard = block_on(mutex.lock());
+        assert_eq!(*guard, 1);
+    }
+
+    #[test]
+    fn test_async_rwlatch_shared_then_excl() {
+        let lock = AsyncRwLatch::init(0usize);
+        {
+            let guard = block_on(lock.acquire_shared());
+            assert_eq!(*guard, 0);
+        }
+        {
+            let mut guard = block_on(lock.acquire_excl());
+            *guard = 7;
+        }
+        let guard = block_on(lock.acquire_shared());
+        assert_eq!(*guard, 7);
+    }
+
+    /// Regression test for a deadlock where `upgrade()` CAS'd from `UPGRADABLE | READER` to `WRITER`
+    /// even though `acquire_upgradable` never sets the reader bit, so the CAS could never succeed.
+    #[test]
+    fn test_async_rwlatch_upgrade() {
+        let lock = AsyncRwLatch::init(41usize);
+        let upgradable = block_on(lock.acquire_upgradable());
+        let mut exclusive = block_on(upgradable.upgrade());
+        *exclusive += 1;
+        drop(exclusive);
+        let guard = block_on(lock.acquire_shared());
+        assert_eq!(*guard, 42);
+    }
+
+    #[test]
+    fn test_async_binary_semaphore() {
+        let sem = AsyncBinarySemaphore::init(false);
+        sem.post();
+        assert!(block_on(sem.wait()));
+    }
+}
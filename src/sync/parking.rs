@@ -0,0 +1,105 @@
+///----------------------------------------------------------------------------------------------------
+/// The author disclaims copyright to this source code. In place of a legal notice, here is a blessing:
+///     May you do good and not evil.
+///     May you find forgiveness for yourself and forgive others.
+///     May you share freely, never taking more than you give.
+///----------------------------------------------------------------------------------------------------
+/// This file implements the default `Synchronized<T>`/`RwSynchronized<T>` backend, built on
+/// `parking_lot::Mutex`/`parking_lot::RwLock` (not `std::sync::Mutex`/`std::sync::RwLock`). Blocked
+/// threads are parked with the OS scheduler rather than busy-waiting; see `sync::spin` for the
+/// `no_std`-friendly alternative selected by the `spin-lock` feature.
+///----------------------------------------------------------------------------------------------------
+use super::{Latch, RwLatch};
+use parking_lot::lock_api::{RawMutex as _, RawRwLock as _, RawRwLockUpgrade as _};
+use parking_lot::{Mutex, RwLock};
+use std::sync::Arc;
+
+/// Protect anything with a Mutex. Can pass between threads (implements the clone trait)
+pub type Synchronized<T> = Arc<Mutex<T>>;
+
+/// Protect anything with a RwLock. Can pass between threads (implements the clone trait).
+pub type RwSynchronized<T> = Arc<RwLock<T>>;
+
+/// The methods here are for latching Synchronized<T> objects *unsafely*. Don't use this unless you have to (prefer RAII guards)
+/// Examples of when you need to use these methods:
+/// - If you need to place a lock on an object in one function and unlock it in another function (i.e. when you can't do everything you)
+///   want in one scope.
+impl<T> Latch<T> for Synchronized<T> {
+    fn init(item: T) -> Self {
+        Arc::new(Mutex::new(item))
+    }
+    fn latch(&self) {
+        unsafe {
+            self.raw().lock();
+        }
+    }
+    fn unlatch(&self) {
+        unsafe {
+            self.raw().unlock();
+        }
+    }
+}
+
+/// The methods here are for latching RwSynchronized<T> objects *unsafely*. Don't use this unless you have to (prefer RAII guards)
+/// Examples of when you need to use these methods:
+/// - If you need to place a lock on an object in one function and unlock it in another function (i.e. when you can't do everything you)
+///   want in one scope.
+impl<T> RwLatch<T> for RwSynchronized<T> {
+    fn init(item: T) -> Self {
+        Arc::new(RwLock::new(item))
+    }
+
+    /// Acquire a shared lock. Must not hold a lock in the current context.
+    fn acquire_shared(&self) {
+        unsafe {
+            self.raw().lock_shared();
+        }
+    }
+
+    /// Acquire an upgradable lock. Must not hold a lock in the current context.
+    fn acquire_upgradable(&self) {
+        unsafe {
+            self.raw().lock_upgradable();
+        }
+    }
+
+    /// Acquire an exclusive lock. Must not hold a lock in the current context.
+    fn acquire_excl(&self) {
+        unsafe {
+            self.raw().lock_exclusive();
+        }
+    }
+
+    /// Release a shared lock. Must hold a shared lock in the current context.
+    fn release_shared(&self) {
+        unsafe {
+            self.raw().unlock_shared();
+        }
+    }
+
+    /// Release an upgradable lock. Must hold an upgradable lock in the current context.
+    fn release_upgradable(&self) {
+        unsafe {
+            self.raw().unlock_upgradable();
+        }
+    }
+
+    /// Release an exclusive lock. Must hold an exclusive lock in the current context (upgradable locks upgraded to exclusive qualify).
+    fn release_excl(&self) {
+        unsafe {
+            self.raw().unlock_exclusive();
+        }
+    }
+
+    /// Upgrade an upgradable lock to an exclusive one. Must hold an upgradable lock in the current context that has not yet been
+    /// upgraded
+    fn upgrade_shared(&self) {
+        unsafe {
+            self.raw().upgrade();
+        }
+    }
+
+    fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(a, b)
+    }
+}
@@ -0,0 +1,102 @@
+///----------------------------------------------------------------------------------------------------
+/// The author disclaims copyright to this source code. In place of a legal notice, here is a blessing:
+///     May you do good and not evil.
+///     May you find forgiveness for yourself and forgive others.
+///     May you share freely, never taking more than you give.
+///----------------------------------------------------------------------------------------------------
+/// This file implements `Sharded<T>`, a fixed-size array of independently-latched shards - the
+/// technique concurrent hash maps use to avoid a single global lock. Rather than one
+/// `RwSynchronized<T>` that every caller contends on, a key is hashed to one of N shards, and callers
+/// only ever contend with others that land on the same shard. `read`/`write` hash a key straight into
+/// the locked access in a single call, rather than exposing a "pick a shard" step and a separate
+/// "lock it" step that callers would otherwise have to get right themselves.
+///----------------------------------------------------------------------------------------------------
+use super::{RwLatch, RwSynchronized};
+use std::hash::{BuildHasher, Hash, RandomState};
+
+pub struct Sharded<T> {
+    shards: Vec<RwSynchronized<T>>,
+    hash_builder: RandomState,
+}
+
+impl<T> Sharded<T> {
+    /// Build a sharded wrapper with one shard per available CPU (falling back to a single shard if
+    /// the platform can't report a count), each initialized by `make_shard`.
+    pub fn new(make_shard: impl FnMut() -> T) -> Self {
+        let shard_count = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        Self::with_shard_count(shard_count, make_shard)
+    }
+
+    /// Build a sharded wrapper with an explicit shard count (rounded up to 1).
+    pub fn with_shard_count(shard_count: usize, mut make_shard: impl FnMut() -> T) -> Self {
+        let shard_count = shard_count.max(1);
+        let shards = (0..shard_count)
+            .map(|_| RwSynchronized::init(make_shard()))
+            .collect();
+        Self {
+            shards,
+            hash_builder: RandomState::new(),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_index<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        (self.hash_builder.hash_one(key) as usize) % self.shards.len()
+    }
+
+    /// Hash `key` to a shard and run `f` against it under a shared latch, in one call.
+    pub fn read<K: Hash + ?Sized, R>(&self, key: &K, f: impl FnOnce(&T) -> R) -> R {
+        let shard = &self.shards[self.shard_index(key)];
+        shard.acquire_shared();
+        let result = f(unsafe { &*shard.data_ptr() });
+        shard.release_shared();
+        result
+    }
+
+    /// Hash `key` to a shard and run `f` against it under an exclusive latch, in one call.
+    pub fn write<K: Hash + ?Sized, R>(&self, key: &K, f: impl FnOnce(&mut T) -> R) -> R {
+        let shard = &self.shards[self.shard_index(key)];
+        shard.acquire_excl();
+        let result = f(unsafe { &mut *shard.data_ptr() });
+        shard.release_excl();
+        result
+    }
+
+    /// Run `f` against every shard in turn, holding each shard's shared latch only for the duration
+    /// of its own call (never all shards locked together).
+    pub fn for_each_shard(&self, mut f: impl FnMut(&T)) {
+        for shard in &self.shards {
+            shard.acquire_shared();
+            f(unsafe { &*shard.data_ptr() });
+            shard.release_shared();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sharded;
+
+    #[test]
+    fn test_shard_routing_is_stable() {
+        let sharded: Sharded<usize> = Sharded::with_shard_count(4, || 0);
+        assert_eq!(sharded.shard_count(), 4);
+        let first = sharded.shard_index(&"hello");
+        let second = sharded.shard_index(&"hello");
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_read_write_land_on_same_shard_state() {
+        let sharded: Sharded<usize> = Sharded::with_shard_count(4, || 0);
+        sharded.write(&"counter", |value| *value += 1);
+        sharded.write(&"counter", |value| *value += 1);
+        let total = sharded.read(&"counter", |value| *value);
+        assert_eq!(total, 2);
+    }
+}
@@ -0,0 +1,378 @@
+///----------------------------------------------------------------------------------------------------
+/// The author disclaims copyright to this source code. In place of a legal notice, here is a blessing:
+///     May you do good and not evil.
+///     May you find forgiveness for yourself and forgive others.
+///     May you share freely, never taking more than you give.
+///----------------------------------------------------------------------------------------------------
+/// This file implements the `spin-lock` backend for `Synchronized<T>`/`RwSynchronized<T>`: busy-wait
+/// locks built on a single `AtomicUsize` state word per lock, with no dependency on OS thread-parking
+/// (`parking_lot`/`std::sync`), so they can run in `no_std` + `alloc` contexts such as embedded
+/// firmware. Select this backend with `--features spin-lock`.
+///
+/// `RwSpinLock<T>`'s state word is laid out as:
+///     - bit `usize::BITS - 1` (`WRITER`): set while a thread holds the exclusive lock.
+///     - bit `usize::BITS - 2` (`UPGRADABLE`): set while a thread holds the upgradable lock. At most
+///       one upgrader may be reserved at a time, which is what makes `upgrade_shared` deadlock-free:
+///       an upgrader never has to contend with a second upgrader for the readers to drain in front of.
+///     - the remaining low bits: a plain count of active shared (reader) holders.
+/// A writer may set `WRITER` only when the whole word is otherwise zero. An upgrader may set
+/// `UPGRADABLE` whenever neither it nor `WRITER` is set, regardless of the reader count, and later upgrades to
+/// `WRITER` once the reader count drains to zero.
+///----------------------------------------------------------------------------------------------------
+use super::{Latch, RwLatch};
+// `alloc` is always available alongside `std`, but isn't implicitly in scope - this backend only
+// needs it for `Arc`, so it's the one extern crate this no_std module requires.
+extern crate alloc;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Determines how a spinning thread behaves between failed attempts to acquire a lock.
+pub trait RelaxStrategy {
+    /// Called once per failed acquisition attempt, before the next retry.
+    fn relax();
+}
+
+/// Spin in place, hinting to the CPU that this is a busy-wait loop. Appropriate when locks are held
+/// for only a handful of instructions, which is the expected case for the tree's latches.
+pub struct SpinRelax;
+impl RelaxStrategy for SpinRelax {
+    fn relax() {
+        core::hint::spin_loop();
+    }
+}
+
+/// Yield the calling thread back to the OS scheduler between spins. Appropriate under heavier
+/// contention, where burning a core spinning is more wasteful than a context switch. Depends on
+/// `std::thread::yield_now`, so it is only available with the standard library - embedded/`no_std`
+/// callers under `spin-lock` should stick to `SpinRelax`.
+#[cfg(feature = "std")]
+pub struct YieldRelax;
+#[cfg(feature = "std")]
+impl RelaxStrategy for YieldRelax {
+    fn relax() {
+        std::thread::yield_now();
+    }
+}
+
+const WRITER: usize = 1 << (usize::BITS - 1);
+const UPGRADABLE: usize = 1 << (usize::BITS - 2);
+const READER: usize = 1;
+
+struct SpinMutexInner<T> {
+    locked: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for SpinMutexInner<T> {}
+unsafe impl<T: Send> Sync for SpinMutexInner<T> {}
+
+/// A spinning mutex parameterized by its relax strategy `R`, holding its state in a shared `Arc`.
+/// `Synchronized<T>` is an alias for `SpinMutex<T, SpinRelax>`; construct a `SpinMutex<T, YieldRelax>` directly if a different
+/// relax strategy is wanted.
+pub struct SpinMutex<T, R: RelaxStrategy = SpinRelax> {
+    inner: Arc<SpinMutexInner<T>>,
+    _relax: core::marker::PhantomData<R>,
+}
+
+impl<T, R: RelaxStrategy> Clone for SpinMutex<T, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _relax: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<T, R: RelaxStrategy> SpinMutex<T, R> {
+    /// Raw pointer to the protected data. Caller must hold the lock.
+    pub fn data_ptr(&self) -> *mut T {
+        self.inner.data.get()
+    }
+}
+
+/// Protect anything with a spinning mutex. Can pass between threads (implements the clone trait).
+pub type Synchronized<T> = SpinMutex<T, SpinRelax>;
+
+impl<T, R: RelaxStrategy> Latch<T> for SpinMutex<T, R> {
+    fn init(item: T) -> Self {
+        Self {
+            inner: Arc::new(SpinMutexInner {
+                locked: AtomicUsize::new(0),
+                data: UnsafeCell::new(item),
+            }),
+            _relax: core::marker::PhantomData,
+        }
+    }
+
+    fn latch(&self) {
+        while self
+            .inner
+            .locked
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            R::relax();
+        }
+    }
+
+    fn unlatch(&self) {
+        self.inner.locked.store(0, Ordering::Release);
+    }
+}
+
+struct SpinRwLockInner<T> {
+    state: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for SpinRwLockInner<T> {}
+unsafe impl<T: Send> Sync for SpinRwLockInner<T> {}
+
+/// A spinning reader/writer lock parameterized by its relax strategy `R`, supporting the same
+/// shared/upgradable/exclusive operations as `parking_lot::RwLock`, holding its state in a shared
+/// `Arc`. `RwSynchronized<T>` is an alias for `RwSpinLock<T, SpinRelax>`.
+pub struct RwSpinLock<T, R: RelaxStrategy = SpinRelax> {
+    inner: Arc<SpinRwLockInner<T>>,
+    _relax: core::marker::PhantomData<R>,
+}
+
+impl<T, R: RelaxStrategy> Clone for RwSpinLock<T, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _relax: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<T, R: RelaxStrategy> RwSpinLock<T, R> {
+    /// Raw pointer to the protected data. Caller must hold at least a shared lock.
+    pub fn data_ptr(&self) -> *mut T {
+        self.inner.data.get()
+    }
+
+    /// Acquire a shared RAII guard, mirroring `parking_lot::RwLock::read` so call sites that only
+    /// need scoped access (rather than the unsafe `RwLatch` latch-crabbing API) work unchanged across
+    /// backends.
+    pub fn read(&self) -> RwSpinLockReadGuard<'_, T, R> {
+        RwLatch::acquire_shared(self);
+        RwSpinLockReadGuard { lock: self }
+    }
+
+    /// Acquire an exclusive RAII guard, mirroring `parking_lot::RwLock::write`.
+    pub fn write(&self) -> RwSpinLockWriteGuard<'_, T, R> {
+        RwLatch::acquire_excl(self);
+        RwSpinLockWriteGuard { lock: self }
+    }
+}
+
+/// RAII guard returned by `RwSpinLock::read`. Releases the shared latch on drop.
+pub struct RwSpinLockReadGuard<'a, T, R: RelaxStrategy> {
+    lock: &'a RwSpinLock<T, R>,
+}
+
+impl<T, R: RelaxStrategy> core::ops::Deref for RwSpinLockReadGuard<'_, T, R> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data_ptr() }
+    }
+}
+
+impl<T, R: RelaxStrategy> Drop for RwSpinLockReadGuard<'_, T, R> {
+    fn drop(&mut self) {
+        RwLatch::release_shared(self.lock);
+    }
+}
+
+/// RAII guard returned by `RwSpinLock::write`. Releases the exclusive latch on drop.
+pub struct RwSpinLockWriteGuard<'a, T, R: RelaxStrategy> {
+    lock: &'a RwSpinLock<T, R>,
+}
+
+impl<T, R: RelaxStrategy> core::ops::Deref for RwSpinLockWriteGuard<'_, T, R> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data_ptr() }
+    }
+}
+
+impl<T, R: RelaxStrategy> core::ops::DerefMut for RwSpinLockWriteGuard<'_, T, R> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data_ptr() }
+    }
+}
+
+impl<T, R: RelaxStrategy> Drop for RwSpinLockWriteGuard<'_, T, R> {
+    fn drop(&mut self) {
+        RwLatch::release_excl(self.lock);
+    }
+}
+
+/// Protect anything with a spinning rwlock. Can pass between threads (implements the clone trait).
+pub type RwSynchronized<T> = RwSpinLock<T, SpinRelax>;
+
+impl<T, R: RelaxStrategy> RwLatch<T> for RwSpinLock<T, R> {
+    fn init(item: T) -> Self {
+        Self {
+            inner: Arc::new(SpinRwLockInner {
+                state: AtomicUsize::new(0),
+                data: UnsafeCell::new(item),
+            }),
+            _relax: core::marker::PhantomData,
+        }
+    }
+
+    /// Acquire a shared lock. Must not hold a lock in the current context.
+    fn acquire_shared(&self) {
+        loop {
+            let state = self.inner.state.load(Ordering::Relaxed);
+            if state & WRITER == 0
+                && self
+                    .inner
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state + READER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                return;
+            }
+            R::relax();
+        }
+    }
+
+    /// Acquire an upgradable lock. Must not hold a lock in the current context.
+    fn acquire_upgradable(&self) {
+        loop {
+            let state = self.inner.state.load(Ordering::Relaxed);
+            if state & (WRITER | UPGRADABLE) == 0
+                && self
+                    .inner
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state | UPGRADABLE,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                return;
+            }
+            R::relax();
+        }
+    }
+
+    /// Acquire an exclusive lock. Must not hold a lock in the current context.
+    fn acquire_excl(&self) {
+        while self
+            .inner
+            .state
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            R::relax();
+        }
+    }
+
+    /// Release a shared lock. Must hold a shared lock in the current context.
+    fn release_shared(&self) {
+        self.inner.state.fetch_sub(READER, Ordering::Release);
+    }
+
+    /// Release an upgradable lock. Must hold an upgradable lock in the current context.
+    fn release_upgradable(&self) {
+        self.inner.state.fetch_and(!UPGRADABLE, Ordering::Release);
+    }
+
+    /// Release an exclusive lock. Must hold an exclusive lock in the current context (upgradable locks upgraded to exclusive qualify).
+    fn release_excl(&self) {
+        self.inner.state.store(0, Ordering::Release);
+    }
+
+    /// Upgrade an upgradable lock to an exclusive one. Must hold an upgradable lock in the current context that has not yet been
+    /// upgraded. Spins until the reader count drains to zero, then swaps the reservation bit for the writer bit.
+    fn upgrade_shared(&self) {
+        loop {
+            if self
+                .inner
+                .state
+                .compare_exchange_weak(UPGRADABLE, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+            R::relax();
+        }
+    }
+
+    fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.inner, &b.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Latch as _, RwLatch as _, RwSynchronized, Synchronized};
+    use rayon::ThreadPoolBuilder;
+
+    struct TestStruct {
+        data: usize,
+    }
+
+    #[test]
+    fn test_spin_mutex_mutual_exclusion() {
+        let sync = Synchronized::init(TestStruct { data: 0 });
+        let pool = ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        pool.scope(|scope| {
+            for _ in 0..8 {
+                let sync = sync.clone();
+                scope.spawn(move |_| {
+                    for _ in 0..1000 {
+                        sync.latch();
+                        unsafe { (*sync.data_ptr()).data += 1 };
+                        sync.unlatch();
+                    }
+                });
+            }
+        });
+        assert_eq!(unsafe { (*sync.data_ptr()).data }, 8000);
+    }
+
+    #[test]
+    fn test_spin_rwlock_readers_see_writer_updates() {
+        let rw = RwSynchronized::init(TestStruct { data: 0 });
+        let pool = ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        pool.scope(|scope| {
+            for _ in 0..8 {
+                let rw = rw.clone();
+                scope.spawn(move |_| {
+                    for _ in 0..1000 {
+                        rw.acquire_excl();
+                        unsafe { (*rw.data_ptr()).data += 1 };
+                        rw.release_excl();
+                    }
+                });
+            }
+        });
+        rw.acquire_shared();
+        assert_eq!(unsafe { (*rw.data_ptr()).data }, 8000);
+        rw.release_shared();
+    }
+
+    #[test]
+    fn test_spin_rwlock_upgrade() {
+        let rw = RwSynchronized::init(TestStruct { data: 41 });
+        rw.acquire_upgradable();
+        rw.upgrade_shared();
+        unsafe { (*rw.data_ptr()).data += 1 };
+        rw.release_excl();
+        rw.acquire_shared();
+        assert_eq!(unsafe { (*rw.data_ptr()).data }, 42);
+        rw.release_shared();
+    }
+}
@@ -0,0 +1,279 @@
+///----------------------------------------------------------------------------------------------------
+/// The author disclaims copyright to this source code. In place of a legal notice, here is a blessing:
+///     May you do good and not evil.
+///     May you find forgiveness for yourself and forgive others.
+///     May you share freely, never taking more than you give.
+///----------------------------------------------------------------------------------------------------
+/// This file implements `TicketRwLock<T>`, a fairness-preserving alternative to the default
+/// `RwSynchronized<T>` backend. `parking_lot::RwLock` (and the spin backend in `sync::spin`) make no
+/// FIFO guarantee: a steady stream of shared acquisitions on a hot node can starve a writer indefinitely,
+/// which matters for the tree's latch-crabbing since a split needs an exclusive latch on a node that
+/// readers are actively descending through. `TicketRwLock<T>` instead serves acquisitions in arrival
+/// order:
+///     - every acquirer atomically fetches-and-increments `next_ticket` to learn its place in line,
+///       then spins until `now_serving` reaches that ticket.
+///     - a reader, once served, joins `readers` and immediately bumps `now_serving` again, so readers
+///       with adjacent tickets batch into the same shared epoch instead of waiting on each other.
+///     - a writer, once served, instead waits for `readers` to drain to zero *before* bumping
+///       `now_serving` on release. Because `now_serving` stalls at the writer's ticket in the
+///       meantime, every ticket issued after the writer's blocks behind it - later readers cannot slip
+///       in ahead of a waiting writer the way they can with `parking_lot::RwLock`.
+///     - draining to zero readers is necessary but not sufficient for exclusive access: an upgrader
+///       can also drain `readers` to zero on its way from an upgradable to an exclusive hold. A
+///       writer and an upgrader racing for the same zero reader count both have to win a CAS on
+///       `writer_claimed` before either treats itself as holding the lock exclusively.
+///----------------------------------------------------------------------------------------------------
+use super::RwLatch;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct TicketRwLockInner<T> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    readers: AtomicUsize,
+    upgradable_taken: AtomicBool,
+    excl_via_upgrade: AtomicBool,
+    writer_claimed: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for TicketRwLockInner<T> {}
+unsafe impl<T: Send> Sync for TicketRwLockInner<T> {}
+
+/// A ticket-ordered reader/writer lock. Construct with `RwLatch::init` like any other `RwLatch<T>`
+/// implementation and use it directly wherever bounded wait times matter more than maximum
+/// throughput.
+///
+/// This is standalone: `Node<T>` (see `node.rs`) is a concrete `RwSynchronized<NodeInner<T>>` type
+/// alias, not generic over `RwLatch<T>`, because `RwSynchronized` itself is a single backend chosen
+/// at compile time by the `spin-lock` feature (see `sync.rs`) rather than a per-instance choice - the
+/// tree has no constructor that takes a lock type to plug in. Backing a `Tree` with
+/// `TicketRwLock<NodeInner<T>>` instead would mean making every node-holding type (`Node<T>`,
+/// `Tree::root`, `ShardedTree`'s shard array) generic over the lock backend, which is a much larger
+/// change than this lock itself; until that's warranted, use `TicketRwLock<T>` on its own for
+/// fairness-sensitive data structures outside the tree.
+pub struct TicketRwLock<T> {
+    inner: Arc<TicketRwLockInner<T>>,
+}
+
+impl<T> Clone for TicketRwLock<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> TicketRwLock<T> {
+    /// Raw pointer to the protected data. Caller must hold at least a shared lock.
+    pub fn data_ptr(&self) -> *mut T {
+        self.inner.data.get()
+    }
+
+    /// Draw a ticket and spin until it is this acquirer's turn to be served.
+    fn take_ticket(&self) -> usize {
+        let ticket = self.inner.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.inner.now_serving.load(Ordering::Acquire) != ticket {
+            std::hint::spin_loop();
+        }
+        ticket
+    }
+}
+
+impl<T> RwLatch<T> for TicketRwLock<T> {
+    fn init(item: T) -> Self {
+        Self {
+            inner: Arc::new(TicketRwLockInner {
+                next_ticket: AtomicUsize::new(0),
+                now_serving: AtomicUsize::new(0),
+                readers: AtomicUsize::new(0),
+                upgradable_taken: AtomicBool::new(false),
+                excl_via_upgrade: AtomicBool::new(false),
+                writer_claimed: AtomicBool::new(false),
+                data: UnsafeCell::new(item),
+            }),
+        }
+    }
+
+    /// Acquire a shared lock. Must not hold a lock in the current context.
+    fn acquire_shared(&self) {
+        self.take_ticket();
+        self.inner.readers.fetch_add(1, Ordering::Acquire);
+        self.inner.now_serving.fetch_add(1, Ordering::Release);
+    }
+
+    /// Acquire an upgradable lock. Must not hold a lock in the current context. Behaves like a
+    /// shared acquisition (so it does not block concurrent readers or the ticket line behind it),
+    /// except that only one upgradable guard may be outstanding at a time: a second upgrader waits
+    /// for `upgradable_taken` to clear, holding up its own ticket (and every ticket behind it) in the
+    /// meantime.
+    fn acquire_upgradable(&self) {
+        self.take_ticket();
+        while self
+            .inner
+            .upgradable_taken
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        self.inner.readers.fetch_add(1, Ordering::Acquire);
+        self.inner.now_serving.fetch_add(1, Ordering::Release);
+    }
+
+    /// Acquire an exclusive lock. Must not hold a lock in the current context. Unlike readers, a
+    /// writer does not bump `now_serving` upon being served - only `release_excl`/`upgrade_shared`
+    /// do - so every ticket behind it stalls until the writer is done, which is what prevents
+    /// starvation. Draining to zero readers isn't itself exclusive access: an upgrader can drain
+    /// `readers` to zero on its way to claiming exclusivity via `upgrade_shared`, so a plain writer
+    /// also has to win the `writer_claimed` flag before treating the zero reader count as its own.
+    fn acquire_excl(&self) {
+        self.take_ticket();
+        loop {
+            if self.inner.readers.load(Ordering::Acquire) == 0
+                && self
+                    .inner
+                    .writer_claimed
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Release a shared lock. Must hold a shared lock in the current context.
+    fn release_shared(&self) {
+        self.inner.readers.fetch_sub(1, Ordering::Release);
+    }
+
+    /// Release an upgradable lock. Must hold an upgradable lock in the current context.
+    fn release_upgradable(&self) {
+        self.inner.readers.fetch_sub(1, Ordering::Release);
+        self.inner.upgradable_taken.store(false, Ordering::Release);
+    }
+
+    /// Release an exclusive lock. Must hold an exclusive lock in the current context (upgradable locks upgraded to exclusive qualify).
+    ///
+    /// Clears `writer_claimed` first so a writer or upgrader spinning in `acquire_excl`/
+    /// `upgrade_shared` can claim exclusivity next. A ticket is only ever served once, so
+    /// `now_serving` must only advance once per ticket. A lock acquired via `acquire_excl` was never
+    /// bumped on service (see above), so this is that bump. A lock reached via `upgrade_shared` was
+    /// already bumped when its upgradable acquisition was served, so this skips the bump for that
+    /// ticket instead of double-advancing it.
+    fn release_excl(&self) {
+        self.inner.writer_claimed.store(false, Ordering::Release);
+        if self.inner.excl_via_upgrade.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        self.inner.now_serving.fetch_add(1, Ordering::Release);
+    }
+
+    /// Upgrade an upgradable lock to an exclusive one. Must hold an upgradable lock in the current
+    /// context that has not yet been upgraded. The upgradable guard already counts as one reader, so
+    /// this waits for `readers` to drain to exactly that one (itself) before claiming exclusivity.
+    /// Draining `readers` to zero is not itself exclusive access - a writer served behind this ticket
+    /// can observe the same zero count in `acquire_excl` - so this also has to win the
+    /// `writer_claimed` flag before proceeding, exactly like a plain writer does. Marks the ticket as
+    /// having reached exclusivity via upgrade so `release_excl` knows not to bump `now_serving` a
+    /// second time for it.
+    fn upgrade_shared(&self) {
+        while self
+            .inner
+            .readers
+            .compare_exchange_weak(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        while self
+            .inner
+            .writer_claimed
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        self.inner.upgradable_taken.store(false, Ordering::Release);
+        self.inner.excl_via_upgrade.store(true, Ordering::Release);
+    }
+
+    fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.inner, &b.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TicketRwLock;
+    use crate::sync::{BinarySemaphore, BinarySemaphoreMethods as _, RwLatch as _};
+    use rayon::ThreadPoolBuilder;
+
+    struct TestStruct {
+        data: usize,
+    }
+
+    #[test]
+    fn test_ticket_rwlock_mutual_exclusion() {
+        let rw = TicketRwLock::init(TestStruct { data: 0 });
+        let pool = ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        pool.scope(|scope| {
+            for _ in 0..8 {
+                let rw = rw.clone();
+                scope.spawn(move |_| {
+                    for _ in 0..500 {
+                        rw.acquire_excl();
+                        unsafe { (*rw.data_ptr()).data += 1 };
+                        rw.release_excl();
+                    }
+                });
+            }
+        });
+        assert_eq!(unsafe { (*rw.data_ptr()).data }, 4000);
+    }
+
+    /// A flood of readers must not starve a waiting writer: once the writer has drawn its ticket,
+    /// every reader behind it in line blocks until the writer is served.
+    #[test]
+    fn test_ticket_rwlock_writer_not_starved_by_readers() {
+        let rw = TicketRwLock::init(TestStruct { data: 0 });
+        let sem = BinarySemaphore::init(false);
+        let pool = ThreadPoolBuilder::new().num_threads(9).build().unwrap();
+        pool.scope(|scope| {
+            for _ in 0..8 {
+                let rw = rw.clone();
+                scope.spawn(move |_| loop {
+                    rw.acquire_shared();
+                    let done = unsafe { (*rw.data_ptr()).data } > 0;
+                    rw.release_shared();
+                    if done {
+                        return;
+                    }
+                });
+            }
+            let rw = rw.clone();
+            let sem = sem.clone();
+            scope.spawn(move |_| {
+                rw.acquire_excl();
+                unsafe { (*rw.data_ptr()).data = 1 };
+                rw.release_excl();
+                sem.post();
+            });
+        });
+        assert!(sem.wait());
+    }
+
+    #[test]
+    fn test_ticket_rwlock_upgrade() {
+        let rw = TicketRwLock::init(TestStruct { data: 41 });
+        rw.acquire_upgradable();
+        rw.upgrade_shared();
+        unsafe { (*rw.data_ptr()).data += 1 };
+        rw.release_excl();
+        rw.acquire_shared();
+        assert_eq!(unsafe { (*rw.data_ptr()).data }, 42);
+        rw.release_shared();
+    }
+}
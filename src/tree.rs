@@ -1,10 +1,306 @@
 #![allow(unused)]
 
+///----------------------------------------------------------------------------------------------------
+/// The author disclaims copyright to this source code. In place of a legal notice, here is a blessing:
+///     May you do good and not evil.
+///     May you find forgiveness for yourself and forgive others.
+///     May you share freely, never taking more than you give.
+///----------------------------------------------------------------------------------------------------
+/// This file implements `Tree`, a concurrent B-link tree (Lehman & Yao, 1981) built on top of the
+/// latch-crabbing primitives in `sync` and the node operations in `node`. Readers never block writers
+/// and writers never block readers that have already passed them: every node carries a high key and a
+/// right link, so a reader that lands on a node that has since been split by a concurrent writer (but
+/// whose parent has not yet been updated to reflect the split) simply follows the right link instead
+/// of restarting from the root. Writers use latch-crabbing: a child is latched before its parent is
+/// released, and an exclusive latch is only ever taken at the node actually being modified (and,
+/// transiently, at an ancestor that needs a new separator key inserted after a child split).
+///----------------------------------------------------------------------------------------------------
 use crate::node::{BTreeNode as _, Node, NodeInner};
 use crate::sync::{
-    BinarySemaphore, BinarySemaphoreMethods as _, Latch as _, RwLatch as _, RwSynchronized,
+    BinarySemaphore, BinarySemaphoreMethods as _, Latch as _, LatchType, RwLatch, RwSynchronized,
     Synchronized,
 };
-pub struct Tree {}
 
-pub trait BTree {}
+/// `Tree` owns the root of a B-link tree behind its own `RwSynchronized` cell, since the root node
+/// itself changes identity whenever the tree grows a new level (on a root split) - every other node
+/// is reached by following links down from here.
+pub struct Tree<T> {
+    root: RwSynchronized<Node<T>>,
+    min_ord: usize,
+}
+
+/// Methods for generic B-link trees
+pub trait BTree<T> {
+    fn new(min_ord: usize) -> Self;
+    fn search(&self, key: &T) -> bool;
+    fn insert(&self, key: T);
+    fn delete(&self, key: &T) -> bool;
+}
+
+impl<T> BTree<T> for Tree<T>
+where
+    T: Ord + PartialOrd + Clone,
+{
+    fn new(min_ord: usize) -> Self {
+        let root = Node::create(min_ord);
+        root.acquire_excl();
+        root.set_root(true);
+        root.release_excl();
+        Self {
+            root: RwSynchronized::init(root),
+            min_ord,
+        }
+    }
+
+    /// Descend from the root to the leaf that would hold `key`, hopping right via `move_right`
+    /// whenever a concurrent split is detected, and report whether the leaf actually holds it.
+    fn search(&self, key: &T) -> bool {
+        let mut current = self.root.read().clone();
+        current.acquire_shared();
+        loop {
+            current = current.move_right(key, LatchType::Shared);
+            if current.is_leaf() {
+                let found = current.has_key(key);
+                current.release_shared();
+                return found;
+            }
+            let child = current.child_at(current.child_index(key));
+            child.acquire_shared();
+            current.release_shared();
+            current = child;
+        }
+    }
+
+    /// Descend latch-crabbing with shared latches, recording the internal nodes visited along the
+    /// way, then take an exclusive latch at the leaf and insert. If the leaf overflows, split it and
+    /// propagate the new separator key upward, re-`move_right`-ing at each ancestor in case it too
+    /// was split by a concurrent writer since we passed through it.
+    fn insert(&self, key: T) {
+        let mut path: Vec<Node<T>> = Vec::new();
+        let mut current = self.root.read().clone();
+        current.acquire_shared();
+        loop {
+            current = current.move_right(&key, LatchType::Shared);
+            if current.is_leaf() {
+                break;
+            }
+            let child = current.child_at(current.child_index(&key));
+            child.acquire_shared();
+            path.push(current.clone());
+            current.release_shared();
+            current = child;
+        }
+        current.release_shared();
+        current.acquire_excl();
+        current = current.move_right(&key, LatchType::Excl);
+        current.insert_key(key);
+
+        if !current.would_overflow() {
+            current.release_excl();
+            return;
+        }
+        let (separator, new_right) = current.split();
+        self.propagate_split(current, separator, new_right, path);
+    }
+
+    /// Remove `key` from the leaf that holds it. If the leaf becomes empty and is not the root, it is
+    /// unlinked from its parent and its `out_link` is pointed at its right sibling so a reader that
+    /// strayed onto it mid-delete can still resume its search.
+    fn delete(&self, key: &T) -> bool {
+        let mut path: Vec<Node<T>> = Vec::new();
+        let mut current = self.root.read().clone();
+        current.acquire_shared();
+        loop {
+            current = current.move_right(key, LatchType::Shared);
+            if current.is_leaf() {
+                break;
+            }
+            let child = current.child_at(current.child_index(key));
+            child.acquire_shared();
+            path.push(current.clone());
+            current.release_shared();
+            current = child;
+        }
+        current.release_shared();
+        current.acquire_excl();
+        current = current.move_right(key, LatchType::Excl);
+        let removed = current.remove_key(key);
+
+        if !removed || current.key_count() > 0 || current.is_root() {
+            current.release_excl();
+            return removed;
+        }
+
+        current.set_out_link(current.right_link());
+        self.unlink_empty_leaf(current, path);
+        removed
+    }
+}
+
+impl<T> Tree<T>
+where
+    T: Ord + PartialOrd + Clone,
+{
+    /// Insert `separator`/`new_right` into each ancestor on `path`, from the bottom up, re-splitting
+    /// and continuing upward whenever the insertion itself causes an overflow. `path` was captured
+    /// under shared latches during the original descent, so by the time a split cascades all the way
+    /// up through it, a concurrent insert may already have grown the tree a level higher than `path`
+    /// knows about. So an exhausted `path` is only treated as "this is the root" after re-checking
+    /// `node.is_root()` under `self.root`'s own lock; if another thread already demoted `node`, its
+    /// real parent (installed by that thread) is rediscovered via `locate_parent` instead of
+    /// fabricating a second new root that would clobber the first.
+    fn propagate_split(
+        &self,
+        mut node: Node<T>,
+        mut separator: T,
+        mut new_right: Node<T>,
+        mut path: Vec<Node<T>>,
+    ) {
+        loop {
+            let parent = match path.pop() {
+                Some(parent) => parent,
+                None => {
+                    let mut root_guard = self.root.write();
+                    if node.is_root() {
+                        node.set_root(false);
+                        node.release_excl();
+                        let new_root = Node::create(self.min_ord);
+                        new_root.acquire_excl();
+                        new_root.set_root(true);
+                        new_root.set_leaf(false);
+                        new_root.set_keys(vec![separator]);
+                        new_root.set_children(vec![node, new_right]);
+                        new_root.release_excl();
+                        *root_guard = new_root;
+                        return;
+                    }
+                    drop(root_guard);
+                    self.locate_parent(&separator, &node)
+                }
+            };
+            node.release_excl();
+            parent.acquire_excl();
+            let parent = parent.move_right(&separator, LatchType::Excl);
+            parent.insert_separator(separator, new_right);
+            if !parent.would_overflow() {
+                parent.release_excl();
+                return;
+            }
+            let (next_separator, next_right) = parent.split();
+            node = parent;
+            separator = next_separator;
+            new_right = next_right;
+        }
+    }
+
+    /// Find the current parent of `node` by descending from the tree's authoritative root under
+    /// shared latches, following the same key-driven route a search for `key` would take. Used when
+    /// `propagate_split`'s ancestor stack is exhausted but `node` turns out not to be the root after
+    /// all - i.e. a concurrent split already grew the tree above it before this thread's path was
+    /// captured - so the parent isn't anywhere in `path` and must be rediscovered from the root down.
+    fn locate_parent(&self, key: &T, node: &Node<T>) -> Node<T> {
+        let mut current = self.root.read().clone();
+        current.acquire_shared();
+        loop {
+            current = current.move_right(key, LatchType::Shared);
+            let child = current.child_at(current.child_index(key));
+            if RwLatch::ptr_eq(&child, node) {
+                current.release_shared();
+                return current;
+            }
+            child.acquire_shared();
+            current.release_shared();
+            current = child;
+        }
+    }
+
+    /// Remove the link to a now-empty leaf from its parent, identifying it by pointer rather than by
+    /// key since an empty node has nothing left to compare against. `leaf` must already carry its
+    /// `out_link` and be held under an exclusive latch, which this releases before latching the
+    /// parent. The parent is not merged or rebalanced if it underflows, and nothing cascades further
+    /// up the tree.
+    fn unlink_empty_leaf(&self, leaf: Node<T>, mut path: Vec<Node<T>>) {
+        leaf.release_excl();
+        let Some(parent) = path.pop() else {
+            return;
+        };
+        parent.acquire_excl();
+        if let Some(index) = parent.child_slot(&leaf) {
+            parent.remove_child(index);
+        }
+        parent.release_excl();
+    }
+
+    /// Collect every key in the tree in ascending order by descending to the leftmost leaf and then
+    /// walking the leaf level's right-link chain, holding a shared latch on only one leaf at a time
+    /// rather than the whole tree at once. Used by `ShardedTree::keys` to enumerate a shard without
+    /// blocking the others.
+    pub fn keys(&self) -> Vec<T> {
+        let mut current = self.root.read().clone();
+        current.acquire_shared();
+        while !current.is_leaf() {
+            let child = current.child_at(0);
+            child.acquire_shared();
+            current.release_shared();
+            current = child;
+        }
+
+        let mut collected = Vec::new();
+        loop {
+            collected.extend(current.keys_snapshot());
+            let next = current.right_link();
+            current.release_shared();
+            match next {
+                Some(next) => {
+                    next.acquire_shared();
+                    current = next;
+                }
+                None => break,
+            }
+        }
+        collected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BTree, Tree};
+    use rayon::ThreadPoolBuilder;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_single_threaded_insert_search_delete() {
+        let tree: Tree<usize> = Tree::new(2);
+        for key in [5, 3, 8, 1, 4, 7, 9, 2, 6, 10, 11, 12, 13, 14, 15] {
+            tree.insert(key);
+        }
+        for key in 1..=15usize {
+            assert!(tree.search(&key));
+        }
+        assert!(!tree.search(&42));
+
+        assert!(tree.delete(&5));
+        assert!(!tree.search(&5));
+        assert!(!tree.delete(&5));
+        assert!(!tree.delete(&100));
+    }
+
+    #[test]
+    fn test_concurrent_insert_and_search() {
+        let tree: Arc<Tree<usize>> = Arc::new(Tree::new(4));
+        let pool = ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        pool.scope(|scope| {
+            for worker in 0..8usize {
+                let tree = tree.clone();
+                scope.spawn(move |_| {
+                    for i in 0..50usize {
+                        tree.insert(worker * 50 + i);
+                    }
+                });
+            }
+        });
+        for key in 0..400usize {
+            assert!(tree.search(&key));
+        }
+    }
+}